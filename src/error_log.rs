@@ -0,0 +1,21 @@
+//! Hook for reporting connection-pool errors to application-level logging
+use std::net::SocketAddr;
+
+/// Why the pool stopped accepting new connections
+pub enum ShutdownReason {
+    AddressStreamClosed,
+}
+
+/// Reports connection-pool errors as they happen, so the application can
+/// log or alert on them; every method has a no-op default, so implementors
+/// only need to override the events they actually care about
+pub trait ErrorLog {
+    type ConnectionError;
+    type SinkError;
+    fn pool_shutting_down(&self, _reason: ShutdownReason) {}
+    fn connection_error(&self, _addr: SocketAddr, _err: Self::ConnectionError) {}
+    /// A freshly connected sink failed its `Health` probe and was dropped
+    /// before joining the ready queue
+    fn health_check_failed(&self, _addr: SocketAddr) {}
+    fn sink_error(&self, _addr: SocketAddr, _err: Self::SinkError) {}
+}
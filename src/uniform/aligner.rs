@@ -0,0 +1,78 @@
+//! Keeps the per-address connection counts even across the address set
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+
+/// Tracks how many connections are currently open or in-flight to each
+/// wanted address, so that `Lazy` can keep the per-host count uniform
+pub struct Aligner {
+    wanted: HashSet<SocketAddr>,
+    counts: HashMap<SocketAddr, u32>,
+}
+
+impl Aligner {
+    pub fn new() -> Aligner {
+        Aligner {
+            wanted: HashSet::new(),
+            counts: HashMap::new(),
+        }
+    }
+    /// Update the set of addresses we should be connected to
+    pub fn update(&mut self, new: Vec<SocketAddr>, old: Vec<SocketAddr>) {
+        for addr in old {
+            self.wanted.remove(&addr);
+            self.counts.remove(&addr);
+        }
+        for addr in new {
+            self.wanted.insert(addr);
+        }
+    }
+    /// Pick the next address that needs another connection, if any
+    pub fn get<F>(&mut self, conn_limit: u32, is_failing: F) -> Option<SocketAddr>
+        where F: Fn(&SocketAddr) -> bool
+    {
+        for addr in &self.wanted {
+            if is_failing(addr) {
+                continue;
+            }
+            let cnt = self.counts.get(addr).cloned().unwrap_or(0);
+            if cnt < conn_limit {
+                *self.counts.entry(*addr).or_insert(0) += 1;
+                return Some(*addr);
+            }
+        }
+        None
+    }
+    /// Return a slot for the address, to be picked up again on next `get`
+    pub fn put(&mut self, addr: SocketAddr) {
+        if let Some(cnt) = self.counts.get_mut(&addr) {
+            *cnt = cnt.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+    use uniform::failures::Blacklist;
+    use super::{Aligner, SocketAddr};
+
+    fn addr(port: u16) -> SocketAddr {
+        format!("127.0.0.1:{}", port).parse().unwrap()
+    }
+
+    #[test]
+    fn get_skips_blacklisted_addresses() {
+        let core = Core::new().unwrap();
+        let mut blist = Blacklist::new(&core.handle(),
+            Duration::from_millis(100), Duration::from_secs(10));
+        let mut aligner = Aligner::new();
+        let (a, b) = (addr(1), addr(2));
+        aligner.update(vec![a, b], Vec::new());
+        blist.record_failure(a);
+
+        // mirrors the `Aligner::get` call in `Lazy::do_connect`
+        let picked = aligner.get(1, |addr| blist.is_failing(*addr));
+        assert_eq!(picked, Some(b));
+    }
+}
@@ -0,0 +1,95 @@
+//! A simple token bucket used to throttle outbound requests
+use std::time::{Duration, Instant};
+
+/// Refills at `rate` tokens per second, up to `capacity` tokens
+pub(crate) struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(tokens_per_second: u32, burst: u32) -> TokenBucket {
+        TokenBucket {
+            rate: tokens_per_second as f64,
+            capacity: burst as f64,
+            tokens: burst as f64,
+            last_refill: Instant::now(),
+        }
+    }
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill);
+        let elapsed_secs =
+            elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        self.tokens = (self.tokens + elapsed_secs * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+    /// Whether a token is available, without consuming it
+    pub(crate) fn peek(&mut self) -> bool {
+        self.refill();
+        self.tokens >= 1.0
+    }
+    /// Consume a token, assuming `peek()` was just checked
+    pub(crate) fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+    /// How long until at least one token is available
+    pub(crate) fn time_to_next(&mut self) -> Duration {
+        self.refill();
+        if self.tokens >= 1.0 {
+            return Duration::new(0, 0);
+        }
+        let missing = 1.0 - self.tokens;
+        Duration::from_millis((missing / self.rate * 1000.0).ceil() as u64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread::sleep;
+    use std::time::Duration;
+    use super::TokenBucket;
+
+    #[test]
+    fn starts_full_up_to_burst() {
+        let mut bucket = TokenBucket::new(10, 3);
+        assert!(bucket.peek());
+        bucket.take();
+        assert!(bucket.peek());
+        bucket.take();
+        assert!(bucket.peek());
+        bucket.take();
+        assert!(!bucket.peek());
+    }
+
+    #[test]
+    fn refills_over_time_but_caps_at_burst() {
+        let mut bucket = TokenBucket::new(1000, 1);
+        bucket.take();
+        assert!(!bucket.peek());
+        sleep(Duration::from_millis(50));
+        assert!(bucket.peek());
+        sleep(Duration::from_millis(50));
+        // still capped at the burst size, not accumulating without bound
+        assert!(bucket.peek());
+        bucket.take();
+        assert!(!bucket.peek());
+    }
+
+    #[test]
+    fn time_to_next_is_zero_when_a_token_is_available() {
+        let mut bucket = TokenBucket::new(10, 1);
+        assert_eq!(bucket.time_to_next(), Duration::new(0, 0));
+    }
+
+    #[test]
+    fn time_to_next_estimates_the_wait_for_an_empty_bucket() {
+        let mut bucket = TokenBucket::new(10, 1);
+        bucket.take();
+        let wait = bucket.time_to_next();
+        assert!(wait > Duration::new(0, 0));
+        assert!(wait <= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,75 @@
+//! Opaque per-connection capability tags and requirement-based routing
+//!
+//! A single pool can front a cluster where only some hosts support a given
+//! feature (read-only replicas vs. the primary, feature-gated endpoints,
+//! and so on). Rather than splitting that into one pool per capability
+//! class, each connection is tagged with a [`Capabilities`] bitmask once
+//! it is established, and requests can be sent wrapped in [`Tagged`] to
+//! require a subset of those bits before a `Controller` is eligible to
+//! carry them.
+use futures::{Future, future};
+use void::Void;
+
+/// An opaque bitmask of capabilities advertised by a connection, or
+/// required by a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    /// No capabilities; as a requirement this matches every connection
+    pub const NONE: Capabilities = Capabilities(0);
+    /// Every capability bit set; the default tag for connections that
+    /// don't implement capability detection
+    pub const ALL: Capabilities = Capabilities(!0);
+
+    pub fn new(bits: u64) -> Capabilities {
+        Capabilities(bits)
+    }
+    /// Whether `self` carries every bit set in `required`
+    pub fn includes(&self, required: Capabilities) -> bool {
+        self.0 & required.0 == required.0
+    }
+}
+
+/// Detects the capabilities of a freshly connected (and health-checked)
+/// sink, so `start_send` can route capability-tagged requests only to
+/// connections that support them
+///
+/// The default, [`NoCapabilities`], tags every connection with
+/// [`Capabilities::ALL`], which preserves plain round-robin behavior for
+/// pools that don't need capability routing.
+pub trait CapabilityProbe<S> {
+    type Future: Future<Item=Capabilities, Error=Void>;
+    fn detect(&self, sink: &S) -> Self::Future;
+}
+
+pub struct NoCapabilities;
+
+impl<S> CapabilityProbe<S> for NoCapabilities {
+    type Future = future::FutureResult<Capabilities, Void>;
+    fn detect(&self, _sink: &S) -> Self::Future {
+        future::ok(Capabilities::ALL)
+    }
+}
+
+/// A request wrapped with the capabilities a connection must advertise
+/// before it's eligible to carry it
+///
+/// Plain items convert into a `Tagged` requiring no capabilities at all,
+/// so existing call sites that just send `I` keep working via `.into()`.
+pub struct Tagged<I> {
+    pub item: I,
+    pub required: Capabilities,
+}
+
+impl<I> Tagged<I> {
+    pub fn new(item: I, required: Capabilities) -> Tagged<I> {
+        Tagged { item, required }
+    }
+}
+
+impl<I> From<I> for Tagged<I> {
+    fn from(item: I) -> Tagged<I> {
+        Tagged { item, required: Capabilities::NONE }
+    }
+}
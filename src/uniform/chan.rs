@@ -0,0 +1,170 @@
+//! The handle pair shared between the pool driver and the background
+//! future that owns the actual connection sink
+use std::cell::RefCell;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use uniform::{Connections, RateLimit};
+use uniform::bucket::TokenBucket;
+use uniform::capability::Capabilities;
+
+struct Inner<I> {
+    addr: SocketAddr,
+    pub(crate) closed: bool,
+    pub(crate) queued: bool,
+    connected_at: Instant,
+    last_used: Instant,
+    pending: Option<I>,
+    bucket: Option<TokenBucket>,
+    capabilities: Capabilities,
+    connections: Weak<RefCell<Connections<I>>>,
+}
+
+/// A handle used by `Lazy` to drive round-robin dispatch into a connection
+pub struct Controller<I> {
+    pub(crate) inner: Rc<RefCell<Inner<I>>>,
+}
+
+/// A handle used by the background connect/sink futures to report progress
+/// back to the controller sitting in the pool's queue
+pub struct Helper<I> {
+    inner: Rc<RefCell<Inner<I>>>,
+}
+
+impl<I> Controller<I> {
+    pub fn addr(&self) -> SocketAddr {
+        self.inner.borrow().addr
+    }
+    pub fn is_closed(&self) -> bool {
+        self.inner.borrow().closed
+    }
+    pub fn close(&self) {
+        self.inner.borrow_mut().closed = true;
+    }
+    pub fn request(&self, value: I) {
+        let mut inner = self.inner.borrow_mut();
+        inner.pending = Some(value);
+        inner.last_used = Instant::now();
+    }
+    pub fn request_back(&self) -> Option<I> {
+        self.inner.borrow_mut().pending.take()
+    }
+    /// The `Instant` the connect attempt for this connection was started
+    /// (time spent connecting and probing counts towards its age)
+    pub fn connected_at(&self) -> Instant {
+        self.inner.borrow().connected_at
+    }
+    /// The `Instant` this connection was last handed a request
+    pub fn last_used(&self) -> Instant {
+        self.inner.borrow().last_used
+    }
+    /// Whether this connection's own bucket has a token to spend, without
+    /// consuming it
+    pub fn has_token(&self) -> bool {
+        self.inner.borrow_mut().bucket.as_mut().map(|b| b.peek()).unwrap_or(true)
+    }
+    /// Spend a token from this connection's own bucket (if any); caller
+    /// must have already checked `has_token`
+    pub fn take_token(&self) {
+        if let Some(bucket) = self.inner.borrow_mut().bucket.as_mut() {
+            bucket.take();
+        }
+    }
+    /// Soonest this connection's own bucket will have a token again
+    pub fn token_wakeup(&self) -> Duration {
+        self.inner.borrow_mut().bucket.as_mut()
+            .map(|b| b.time_to_next())
+            .unwrap_or_else(|| Duration::new(0, 0))
+    }
+    /// The capabilities advertised by this connection, as of the last
+    /// `CapabilityProbe::detect`
+    pub fn capabilities(&self) -> Capabilities {
+        self.inner.borrow().capabilities
+    }
+}
+
+impl<I> Clone for Controller<I> {
+    fn clone(&self) -> Controller<I> {
+        Controller { inner: self.inner.clone() }
+    }
+}
+
+impl<I> PartialEq for Controller<I> {
+    fn eq(&self, other: &Controller<I>) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+impl<I> Eq for Controller<I> {}
+
+impl<I> Hash for Controller<I> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.inner) as *const () as usize).hash(state)
+    }
+}
+
+impl<I> Helper<I> {
+    pub fn new(addr: SocketAddr, connections: Rc<RefCell<Connections<I>>>,
+        rate_limit: Option<RateLimit>) -> Helper<I>
+    {
+        let now = Instant::now();
+        Helper {
+            inner: Rc::new(RefCell::new(Inner {
+                addr,
+                closed: false,
+                queued: false,
+                connected_at: now,
+                last_used: now,
+                pending: None,
+                bucket: rate_limit.map(|r| {
+                    TokenBucket::new(r.tokens_per_second, r.burst)
+                }),
+                capabilities: Capabilities::ALL,
+                connections: Rc::downgrade(&connections),
+            })),
+        }
+    }
+    pub fn controller(&self) -> Controller<I> {
+        Controller { inner: self.inner.clone() }
+    }
+    pub fn addr(&self) -> SocketAddr {
+        self.inner.borrow().addr
+    }
+    pub fn is_closed(&self) -> bool {
+        self.inner.borrow().closed
+    }
+    /// The `Instant` the connect attempt for this connection was started
+    pub fn connected_at(&self) -> Instant {
+        self.inner.borrow().connected_at
+    }
+    /// Record the capabilities detected for this connection, making it
+    /// eligible to carry requests that require them
+    pub fn set_capabilities(&self, capabilities: Capabilities) {
+        self.inner.borrow_mut().capabilities = capabilities;
+    }
+    /// Take the item currently queued for this connection, if any
+    pub fn take_pending(&self) -> Option<I> {
+        self.inner.borrow_mut().pending.take()
+    }
+    /// Put an item back because the sink wasn't ready to take it
+    pub fn put_back(&self, value: I) {
+        self.inner.borrow_mut().pending = Some(value);
+    }
+    /// Re-insert the controller into the ready queue, if it isn't already
+    /// queued or closed
+    pub fn requeue(&self) {
+        let ctr = self.controller();
+        let connections = match self.inner.borrow().connections.upgrade() {
+            Some(c) => c,
+            None => return,
+        };
+        let already_queued = {
+            let inner = self.inner.borrow();
+            inner.queued || inner.closed
+        };
+        if !already_queued {
+            connections.borrow_mut().add(ctr);
+        }
+    }
+}
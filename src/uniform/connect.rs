@@ -0,0 +1,117 @@
+//! Drives a single connect attempt to completion
+use std::marker::PhantomData;
+
+use futures::{Future, Async, Sink};
+use void::unreachable;
+
+use uniform::{FutureOk, FutureErr};
+use uniform::capability::CapabilityProbe;
+use uniform::chan::Helper;
+use uniform::health::Health;
+
+/// Wraps a `Connect::Future`, turning its outcome into a `FutureOk`/
+/// `FutureErr` event understood by `Lazy::poll_futures`
+pub(crate) struct ConnectFuture<F, I> {
+    task: Option<Helper<I>>,
+    connecting: F,
+}
+
+impl<F, I> ConnectFuture<F, I> {
+    pub(crate) fn new(task: Helper<I>, connecting: F) -> ConnectFuture<F, I> {
+        ConnectFuture { task: Some(task), connecting }
+    }
+}
+
+impl<F, I> Future for ConnectFuture<F, I>
+    where F: Future,
+          F::Item: Sink<SinkItem=I>,
+{
+    type Item = FutureOk<F::Item>;
+    type Error = FutureErr<F::Error, <F::Item as Sink>::SinkError>;
+    fn poll(&mut self) -> Result<Async<Self::Item>, Self::Error> {
+        match self.connecting.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(sink)) => {
+                let task = self.task.take().expect("poll after ready");
+                Ok(Async::Ready(FutureOk::Connected(task, sink)))
+            }
+            Err(e) => {
+                let task = self.task.take().expect("poll after ready");
+                Err(FutureErr::CantConnect(task.addr(), e))
+            }
+        }
+    }
+}
+
+/// Runs a [`Health`] probe against a freshly connected sink before it is
+/// trusted to join the ready queue; a failing probe is routed through the
+/// same blacklist/backoff path as a failed connect attempt
+pub(crate) struct ProbeFuture<S: Sink, H: Health<S>, E> {
+    task: Option<Helper<S::SinkItem>>,
+    sink: Option<S>,
+    probe: H::Future,
+    _connect_error: PhantomData<E>,
+}
+
+impl<S: Sink, H: Health<S>, E> ProbeFuture<S, H, E> {
+    pub(crate) fn new(task: Helper<S::SinkItem>, sink: S, health: &H) -> ProbeFuture<S, H, E> {
+        let probe = health.check(&sink);
+        ProbeFuture { task: Some(task), sink: Some(sink), probe, _connect_error: PhantomData }
+    }
+}
+
+impl<S: Sink, H: Health<S>, E> Future for ProbeFuture<S, H, E> {
+    type Item = FutureOk<S>;
+    type Error = FutureErr<E, S::SinkError>;
+    fn poll(&mut self) -> Result<Async<Self::Item>, Self::Error> {
+        match self.probe.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(true)) => {
+                let task = self.task.take().expect("poll after ready");
+                let sink = self.sink.take().expect("poll after ready");
+                Ok(Async::Ready(FutureOk::Probed(task, sink)))
+            }
+            Ok(Async::Ready(false)) | Err(()) => {
+                let task = self.task.take().expect("poll after ready");
+                Err(FutureErr::Unhealthy(task.addr()))
+            }
+        }
+    }
+}
+
+/// Tags a health-checked sink with the capabilities it advertises, before
+/// it is allowed to carry capability-tagged requests
+pub(crate) struct CapabilityFuture<S: Sink, P: CapabilityProbe<S>, E> {
+    task: Option<Helper<S::SinkItem>>,
+    sink: Option<S>,
+    probe: P::Future,
+    _connect_error: PhantomData<E>,
+}
+
+impl<S: Sink, P: CapabilityProbe<S>, E> CapabilityFuture<S, P, E> {
+    pub(crate) fn new(task: Helper<S::SinkItem>, sink: S, capabilities: &P)
+        -> CapabilityFuture<S, P, E>
+    {
+        let probe = capabilities.detect(&sink);
+        CapabilityFuture {
+            task: Some(task), sink: Some(sink), probe,
+            _connect_error: PhantomData,
+        }
+    }
+}
+
+impl<S: Sink, P: CapabilityProbe<S>, E> Future for CapabilityFuture<S, P, E> {
+    type Item = FutureOk<S>;
+    type Error = FutureErr<E, S::SinkError>;
+    fn poll(&mut self) -> Result<Async<Self::Item>, Self::Error> {
+        match self.probe.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(caps)) => {
+                let task = self.task.take().expect("poll after ready");
+                let sink = self.sink.take().expect("poll after ready");
+                Ok(Async::Ready(FutureOk::Tagged(task, sink, caps)))
+            }
+            Err(e) => unreachable(e),
+        }
+    }
+}
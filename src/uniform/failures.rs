@@ -0,0 +1,149 @@
+//! Tracks addresses that recently failed to connect, with a decorrelated
+//! jitter backoff so a hard-down host isn't hammered at a fixed cadence
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use futures::{Async, Stream};
+use futures::stream::FuturesUnordered;
+use rand::{thread_rng, Rng};
+use tokio_core::reactor::{Handle, Timeout};
+
+/// A set of addresses that are temporarily excluded from the aligner
+/// because they recently failed to connect
+pub struct Blacklist {
+    handle: Handle,
+    base: Duration,
+    cap: Duration,
+    expiry: HashMap<SocketAddr, Instant>,
+    sleep: HashMap<SocketAddr, Duration>,
+    timeouts: FuturesUnordered<Timeout>,
+}
+
+impl Blacklist {
+    pub fn new(handle: &Handle, base: Duration, cap: Duration) -> Blacklist {
+        Blacklist {
+            handle: handle.clone(),
+            base, cap,
+            expiry: HashMap::new(),
+            sleep: HashMap::new(),
+            timeouts: FuturesUnordered::new(),
+        }
+    }
+    /// Whether the address is currently blacklisted
+    pub fn is_failing(&self, addr: SocketAddr) -> bool {
+        self.expiry.get(&addr).map(|&t| t > Instant::now()).unwrap_or(false)
+    }
+    /// Compute the next decorrelated-jitter sleep for a failing address:
+    /// `sleep = min(cap, random_between(base, sleep * 3))`
+    fn next_sleep(&mut self, addr: SocketAddr) -> Duration {
+        let prev = self.sleep.get(&addr).cloned().unwrap_or(self.base);
+        let upper = millis(prev) * 3;
+        let lower = millis(self.base);
+        let next = if upper > lower {
+            thread_rng().gen_range(lower, upper + 1)
+        } else {
+            lower
+        };
+        let next = Duration::from_millis(next).min(self.cap);
+        self.sleep.insert(addr, next);
+        next
+    }
+    /// Record a failed connection attempt or a dropped connection, growing
+    /// the address's backoff, and blacklist it for the computed duration
+    pub fn record_failure(&mut self, addr: SocketAddr) {
+        let sleep = self.next_sleep(addr);
+        self.blacklist(addr, Instant::now() + sleep);
+    }
+    /// Record that a connection to this address stayed up long enough to
+    /// be considered stable again, resetting its backoff to `base`
+    pub fn note_success(&mut self, addr: SocketAddr) {
+        self.sleep.remove(&addr);
+    }
+    /// Blacklist the address until the given instant
+    pub fn blacklist(&mut self, addr: SocketAddr, until: Instant) {
+        self.expiry.insert(addr, until);
+        let now = Instant::now();
+        let dur = if until > now { until - now } else { Duration::new(0, 0) };
+        if let Ok(timeout) = Timeout::new(dur, &self.handle) {
+            self.timeouts.push(timeout);
+        }
+    }
+    /// Poll for blacklist entries that might have expired
+    pub fn poll(&mut self) -> Async<()> {
+        match self.timeouts.poll() {
+            Ok(Async::Ready(Some(()))) => {
+                let now = Instant::now();
+                self.expiry.retain(|_, t| *t > now);
+                Async::Ready(())
+            }
+            _ => Async::NotReady,
+        }
+    }
+}
+
+fn millis(d: Duration) -> u64 {
+    d.as_secs() * 1000 + (d.subsec_nanos() / 1_000_000) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+    use super::{Blacklist, millis};
+
+    fn addr() -> ::std::net::SocketAddr {
+        "127.0.0.1:1".parse().unwrap()
+    }
+
+    #[test]
+    fn first_sleep_is_between_base_and_three_times_base() {
+        let core = Core::new().unwrap();
+        let mut blist = Blacklist::new(&core.handle(),
+            Duration::from_millis(100), Duration::from_secs(10));
+        let sleep = millis(blist.next_sleep(addr()));
+        assert!(sleep >= 100 && sleep <= 300);
+    }
+
+    #[test]
+    fn sleep_grows_with_decorrelated_jitter() {
+        let core = Core::new().unwrap();
+        let mut blist = Blacklist::new(&core.handle(),
+            Duration::from_millis(100), Duration::from_secs(10));
+        let a = addr();
+        let mut prev = millis(blist.next_sleep(a));
+        for _ in 0..10 {
+            let next = millis(blist.next_sleep(a));
+            // decorrelated jitter: always between base and 3x the previous
+            assert!(next >= 100);
+            assert!(next <= prev * 3 + 1);
+            prev = next;
+        }
+    }
+
+    #[test]
+    fn sleep_is_capped() {
+        let core = Core::new().unwrap();
+        let mut blist = Blacklist::new(&core.handle(),
+            Duration::from_millis(100), Duration::from_millis(250));
+        let a = addr();
+        for _ in 0..50 {
+            let sleep = blist.next_sleep(a);
+            assert!(sleep <= Duration::from_millis(250));
+        }
+    }
+
+    #[test]
+    fn note_success_resets_sleep_to_base() {
+        let core = Core::new().unwrap();
+        let mut blist = Blacklist::new(&core.handle(),
+            Duration::from_millis(100), Duration::from_secs(10));
+        let a = addr();
+        for _ in 0..10 {
+            blist.next_sleep(a);
+        }
+        blist.note_success(a);
+        let sleep = millis(blist.next_sleep(a));
+        assert!(sleep >= 100 && sleep <= 300);
+    }
+}
@@ -0,0 +1,26 @@
+//! Pluggable health probe run before a freshly connected sink is allowed
+//! to join the ready queue
+use futures::{Future, future};
+
+/// Validates a freshly established connection before it is trusted with
+/// real requests
+///
+/// Catches half-open TCP connections and failed handshakes that would
+/// otherwise be inserted as "ready" and fail only on the first real
+/// request. Implement this for your own probe type (e.g. a ping), or use
+/// [`NoHealth`] (the default) to skip probing entirely.
+pub trait Health<S> {
+    type Future: Future<Item=bool, Error=()>;
+    fn check(&self, sink: &S) -> Self::Future;
+}
+
+/// The default `Health` implementation: every connection is trusted
+/// immediately, without probing
+pub struct NoHealth;
+
+impl<S> Health<S> for NoHealth {
+    type Future = future::FutureResult<bool, ()>;
+    fn check(&self, _sink: &S) -> Self::Future {
+        future::ok(true)
+    }
+}
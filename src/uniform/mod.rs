@@ -6,9 +6,12 @@
 //! 2. Distributes requests by round-robin until pushback happens
 //!
 mod aligner;
+mod bucket;
+mod capability;
 mod chan;
 mod connect;
 mod failures;
+mod health;
 mod sink;
 mod pool;
 
@@ -21,8 +24,7 @@ use std::time::{Duration, Instant};
 use abstract_ns::Address;
 use futures::{Future, Async, Sink, AsyncSink, Stream};
 use futures::stream::FuturesUnordered;
-use rand::{thread_rng, Rng};
-use tokio_core::reactor::Handle;
+use tokio_core::reactor::{Handle, Timeout};
 use void::{Void, unreachable};
 
 use config::{NewMux, private};
@@ -30,46 +32,102 @@ use error_log::{ErrorLog, ShutdownReason};
 use connect::Connect;
 use metrics::Collect;
 use uniform::aligner::Aligner;
+use uniform::bucket::TokenBucket;
+use uniform::capability::{Capabilities, CapabilityProbe, NoCapabilities, Tagged};
 use uniform::chan::{Controller, Helper};
-use uniform::connect::ConnectFuture;
+use uniform::connect::{ConnectFuture, ProbeFuture, CapabilityFuture};
 use uniform::failures::Blacklist;
+use uniform::health::{Health, NoHealth};
 use uniform::sink::SinkFuture;
 use uniform::pool::Lazy;
 
 
-enum FutureOk<S>
+pub(crate) enum FutureOk<S>
     where S: Sink
 {
+    /// Just connected; still needs to pass the health probe
     Connected(Helper<S::SinkItem>, S),
+    /// Passed the health probe; still needs its capabilities detected
+    Probed(Helper<S::SinkItem>, S),
+    /// Tagged with its advertised capabilities and ready to be driven by
+    /// `SinkFuture`
+    Tagged(Helper<S::SinkItem>, S, Capabilities),
     /// Aborted connect attempt (i.e. when establishing or handshaking)
     Aborted(SocketAddr),
     /// Closed working connection
     Closed(SocketAddr),
 }
 
-enum FutureErr<E, F> {
+pub(crate) enum FutureErr<E, F> {
     CantConnect(SocketAddr, E),
-    Disconnected(SocketAddr, F),
+    /// A working connection dropped; carries how long it had been up so
+    /// the caller can tell a stable connection from a flapping one
+    Disconnected(SocketAddr, F, Duration),
+    /// Connected but failed its health probe
+    Unhealthy(SocketAddr),
+}
+
+/// A token-bucket description for throttling outbound requests
+///
+/// Applied both per-connection and pool-wide (shared across every
+/// connection of a `LazyUniform` pool): a request only goes out once
+/// both buckets have a token to spend.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub tokens_per_second: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(tokens_per_second: u32, burst: u32) -> RateLimit {
+        RateLimit { tokens_per_second, burst }
+    }
 }
 
 /// A constructor for a uniform connection pool with lazy connections
-pub struct LazyUniform {
+pub struct LazyUniform<H=NoHealth, P=NoCapabilities> {
     pub(crate) conn_limit: u32,
     pub(crate) reconnect_timeout: Duration,
+    pub(crate) backoff_cap: Option<Duration>,
+    pub(crate) max_lifetime: Option<Duration>,
+    pub(crate) idle_timeout: Option<Duration>,
+    pub(crate) eager_connect: bool,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) health: H,
+    pub(crate) capabilities: P,
 }
 
 struct Connections<I> {
     queue: VecDeque<Controller<I>>,
     all: HashSet<Controller<I>>,
+    bucket: Option<TokenBucket>,
 }
 
 impl<I> Connections<I> {
-    fn new() -> Connections<I>{
+    fn new(rate_limit: Option<RateLimit>) -> Connections<I>{
         Connections {
             queue: VecDeque::new(),
             all: HashSet::new(),
+            bucket: rate_limit.map(|r| TokenBucket::new(r.tokens_per_second, r.burst)),
+        }
+    }
+    /// Whether the pool-wide bucket (if any) has a token to spend, without
+    /// consuming it
+    fn has_pool_token(&mut self) -> bool {
+        self.bucket.as_mut().map(|b| b.peek()).unwrap_or(true)
+    }
+    /// Spend a token from the pool-wide bucket (if any); caller must have
+    /// already checked `has_pool_token`
+    fn take_pool_token(&mut self) {
+        if let Some(bucket) = self.bucket.as_mut() {
+            bucket.take();
         }
     }
+    /// Soonest the pool-wide bucket (if any) will have a token again
+    fn pool_wakeup(&mut self) -> Duration {
+        self.bucket.as_mut().map(|b| b.time_to_next())
+            .unwrap_or_else(|| Duration::new(0, 0))
+    }
     fn add(&mut self, ctr: Controller<I>) {
         {
             let mut inner = ctr.inner.borrow_mut();
@@ -82,19 +140,57 @@ impl<I> Connections<I> {
     fn has_ready(&self) -> bool {
         self.queue.len() > 0
     }
-    fn next(&mut self) -> Option<Controller<I>> {
-        self.queue.pop_front()
-        .map(|ctr| {
-            {
-                let mut inner = ctr.inner.borrow_mut();
-                assert!(inner.queued);
-                inner.queued = false;
+    /// Whether any non-closed queued connection advertises `required`
+    fn has_ready_matching(&self, required: Capabilities) -> bool {
+        self.queue.iter()
+            .any(|ctr| !ctr.is_closed() && ctr.capabilities().includes(required))
+    }
+    /// How many non-closed queued connections advertise `required`
+    fn count_matching(&self, required: Capabilities) -> usize {
+        self.queue.iter()
+            .filter(|ctr| !ctr.is_closed() && ctr.capabilities().includes(required))
+            .count()
+    }
+    /// Dequeue the first connection that advertises `required`, skipping
+    /// over (and leaving queued) any that don't
+    fn next_matching(&mut self, required: Capabilities) -> Option<Controller<I>> {
+        let pos = self.queue.iter()
+            .position(|ctr| ctr.capabilities().includes(required))?;
+        let ctr = self.queue.remove(pos).expect("position just found");
+        {
+            let mut inner = ctr.inner.borrow_mut();
+            assert!(inner.queued);
+            inner.queued = false;
+        }
+        Some(ctr)
+    }
+}
+/// Close every non-closed connection that has exceeded `max_lifetime` or
+/// has been idle longer than `idle_timeout`
+fn sweep_expired_impl<I>(connections: &Connections<I>,
+    max_lifetime: Option<Duration>, idle_timeout: Option<Duration>, now: Instant)
+{
+    if max_lifetime.is_none() && idle_timeout.is_none() {
+        return;
+    }
+    for ctr in &connections.all {
+        if ctr.is_closed() {
+            continue;
+        }
+        if let Some(limit) = max_lifetime {
+            if now.duration_since(ctr.connected_at()) >= limit {
+                ctr.close();
+                continue;
+            }
+        }
+        if let Some(limit) = idle_timeout {
+            if now.duration_since(ctr.last_used()) >= limit {
+                ctr.close();
             }
-            ctr
-        })
+        }
     }
 }
-impl<A, C, E, M> NewMux<A, C, E, M> for LazyUniform
+impl<A, C, E, M, H, P> NewMux<A, C, E, M> for LazyUniform<H, P>
     where A: Stream<Item=Address, Error=Void>,
           C: Connect + 'static,
           <<C as Connect>::Future as Future>::Item: Sink,
@@ -104,9 +200,11 @@ impl<A, C, E, M> NewMux<A, C, E, M> for LazyUniform
             >,
           E: 'static,
           M: Collect + 'static,
+          H: Health<<C::Future as Future>::Item> + 'static,
+          P: CapabilityProbe<<C::Future as Future>::Item> + 'static,
 {}
 
-impl<A, C, E, M> private::NewMux<A, C, E, M> for LazyUniform
+impl<A, C, E, M, H, P> private::NewMux<A, C, E, M> for LazyUniform<H, P>
     where A: Stream<Item=Address, Error=Void>,
           C: Connect + 'static,
           <<C as Connect>::Future as Future>::Item: Sink,
@@ -116,20 +214,29 @@ impl<A, C, E, M> private::NewMux<A, C, E, M> for LazyUniform
             >,
           E: 'static,
           M: Collect + 'static,
+          H: Health<<C::Future as Future>::Item> + 'static,
+          P: CapabilityProbe<<C::Future as Future>::Item> + 'static,
 {
-    type Sink = Lazy<A, C, E, M>;
+    type Sink = Lazy<A, C, E, M, H, P>;
     fn construct(self,
         h: &Handle, address: A, connector: C, errors: E, metrics: M)
-        -> Lazy<A, C, E, M>
+        -> Lazy<A, C, E, M, H, P>
     {
-        let reconn_ms = self.reconnect_timeout.as_secs() * 1000 +
-            (self.reconnect_timeout.subsec_nanos() / 1000_000) as u64;
+        let cap = self.backoff_cap.unwrap_or(self.reconnect_timeout * 32);
         Lazy {
             conn_limit: self.conn_limit,
-            reconnect_ms: (reconn_ms / 2, reconn_ms * 3 / 2),
+            stable_after: self.reconnect_timeout,
+            max_lifetime: self.max_lifetime,
+            idle_timeout: self.idle_timeout,
+            eager_connect: self.eager_connect,
+            rate_limit: self.rate_limit,
+            rate_wakeup: None,
+            health: self.health,
+            capabilities: self.capabilities,
+            handle: h.clone(),
             futures: FuturesUnordered::new(),
-            connections: Rc::new(RefCell::new(Connections::new())),
-            blist: Blacklist::new(h),
+            connections: Rc::new(RefCell::new(Connections::new(self.rate_limit))),
+            blist: Blacklist::new(h, self.reconnect_timeout, cap),
             aligner: Aligner::new(),
             closing: false,
             cur_address: [][..].into(),
@@ -138,7 +245,84 @@ impl<A, C, E, M> private::NewMux<A, C, E, M> for LazyUniform
     }
 }
 
-impl<A, C, E, M> Lazy<A, C, E, M>
+impl<H, P> LazyUniform<H, P> {
+    /// Proactively recycle a connection once it has been open this long,
+    /// regardless of how busy it is
+    ///
+    /// Useful for rotating connections behind a load balancer that may
+    /// have changed membership since the connection was established.
+    pub fn max_lifetime(mut self, limit: Duration) -> Self {
+        self.max_lifetime = Some(limit);
+        self
+    }
+    /// Proactively recycle a connection once it has been idle this long
+    pub fn idle_timeout(mut self, limit: Duration) -> Self {
+        self.idle_timeout = Some(limit);
+        self
+    }
+    /// Cap the per-address reconnect backoff at this duration
+    ///
+    /// Defaults to 32 times `reconnect_timeout`.
+    pub fn backoff_cap(mut self, cap: Duration) -> Self {
+        self.backoff_cap = Some(cap);
+        self
+    }
+    /// Race a speculative connect against idle checkout
+    ///
+    /// When enabled, `start_send` kicks off a new `ConnectFuture` as soon
+    /// as the ready queue is momentarily empty, instead of waiting for
+    /// round-robin to exhaust the queue first. This trades a little extra
+    /// connection churn for lower tail latency under bursty load.
+    pub fn eager_connect(mut self) -> Self {
+        self.eager_connect = true;
+        self
+    }
+    /// Cap outbound request throughput, per connection and per pool
+    ///
+    /// Each `Controller` gets its own token bucket so traffic stays
+    /// spread across the pool, and `Lazy` keeps a shared bucket so the
+    /// whole pool never exceeds `rate.tokens_per_second` in aggregate.
+    pub fn rate_limit(mut self, rate: RateLimit) -> Self {
+        self.rate_limit = Some(rate);
+        self
+    }
+    /// Validate every freshly connected sink with `health` before it is
+    /// trusted to join the ready queue
+    ///
+    /// A probe that fails (resolves `false` or errors) is routed through
+    /// the same blacklist/backoff path as a failed connect attempt.
+    pub fn health<H2>(self, health: H2) -> LazyUniform<H2, P> {
+        LazyUniform {
+            conn_limit: self.conn_limit,
+            reconnect_timeout: self.reconnect_timeout,
+            backoff_cap: self.backoff_cap,
+            max_lifetime: self.max_lifetime,
+            idle_timeout: self.idle_timeout,
+            eager_connect: self.eager_connect,
+            rate_limit: self.rate_limit,
+            health,
+            capabilities: self.capabilities,
+        }
+    }
+    /// Tag every health-checked connection with the capabilities reported
+    /// by `probe`, so requests wrapped in [`Tagged`] only route to
+    /// connections that advertise the capabilities they require
+    pub fn detect_capabilities<P2>(self, probe: P2) -> LazyUniform<H, P2> {
+        LazyUniform {
+            conn_limit: self.conn_limit,
+            reconnect_timeout: self.reconnect_timeout,
+            backoff_cap: self.backoff_cap,
+            max_lifetime: self.max_lifetime,
+            idle_timeout: self.idle_timeout,
+            eager_connect: self.eager_connect,
+            rate_limit: self.rate_limit,
+            health: self.health,
+            capabilities: probe,
+        }
+    }
+}
+
+impl<A, C, E, M, H, P> Lazy<A, C, E, M, H, P>
     where A: Stream<Item=Address, Error=Void>,
           C: Connect + 'static,
           <<C as Connect>::Future as Future>::Item: Sink,
@@ -147,6 +331,8 @@ impl<A, C, E, M> Lazy<A, C, E, M>
             SinkError=<<C::Future as Future>::Item as Sink>::SinkError,
           >,
           M: Collect + 'static,
+          H: Health<<C::Future as Future>::Item> + 'static,
+          P: CapabilityProbe<<C::Future as Future>::Item> + 'static,
 {
     fn new_addr(&mut self) -> Option<Address> {
         let mut result = None;
@@ -191,10 +377,10 @@ impl<A, C, E, M> Lazy<A, C, E, M>
     }
     fn do_connect(&mut self) -> Option<SocketAddr> {
         let ref blist = self.blist;
-        let new = self.aligner.get(self.conn_limit, |a| blist.is_failing(a));
+        let new = self.aligner.get(self.conn_limit, |a| blist.is_failing(*a));
         if let Some(addr) = new {
             self.metrics.connection_attempt();
-            let task = Helper::new(addr, self.connections.clone());
+            let task = Helper::new(addr, self.connections.clone(), self.rate_limit);
             self.connections.borrow_mut()
                 .all.insert(task.controller());
             self.futures.push(
@@ -205,6 +391,71 @@ impl<A, C, E, M> Lazy<A, C, E, M>
         }
         return None;
     }
+    /// Close connections that have exceeded `max_lifetime` or have been
+    /// idle longer than `idle_timeout`, letting the aligner re-establish
+    /// them on the next `do_connect` so the per-host count stays uniform
+    fn sweep_expired(&mut self) {
+        sweep_expired_impl(&self.connections.borrow(),
+            self.max_lifetime, self.idle_timeout, Instant::now());
+    }
+    /// In eager-connect mode, kick off a connect the moment no queued
+    /// connection advertises `required`, instead of waiting for
+    /// round-robin to exhaust the queue. The in-flight `ConnectFuture` is
+    /// never dropped: if a `Controller` becomes ready before it resolves,
+    /// it just lands in the pool for the next request once `poll_futures`
+    /// picks it up.
+    fn maybe_speculative_connect(&mut self, required: Capabilities) {
+        if !self.eager_connect {
+            return;
+        }
+        if !self.connections.borrow().has_ready_matching(required) {
+            self.do_connect();
+            self.poll_futures();
+        }
+    }
+    /// Spend a token from both the connection's own bucket and the
+    /// pool-wide bucket, or neither if either is empty
+    fn try_take_tokens(&mut self, ctr: &Controller<
+        <<C::Future as Future>::Item as Sink>::SinkItem>) -> bool
+    {
+        if !ctr.has_token() {
+            return false;
+        }
+        if !self.connections.borrow_mut().has_pool_token() {
+            return false;
+        }
+        ctr.take_token();
+        self.connections.borrow_mut().take_pool_token();
+        true
+    }
+    /// Arm a timer for the soonest moment any throttled connection (or
+    /// the pool-wide bucket) will have a token again
+    fn schedule_rate_wakeup(&mut self) {
+        let mut wait = self.connections.borrow_mut().pool_wakeup();
+        for ctr in &self.connections.borrow().queue {
+            let w = ctr.token_wakeup();
+            if w < wait {
+                wait = w;
+            }
+        }
+        if let Ok(mut timeout) = Timeout::new(wait, &self.handle) {
+            let _ = timeout.poll();
+            self.rate_wakeup = Some(timeout);
+        }
+    }
+    /// Re-poll the armed rate-limit timer (if any), the same way `blist`
+    /// is re-polled every turn, so a throttled sink actually un-blocks
+    /// once tokens refill instead of relying on the single registration
+    /// done when the timer was first armed
+    fn poll_rate_wakeup(&mut self) {
+        let fired = match self.rate_wakeup {
+            Some(ref mut timeout) => timeout.poll().unwrap_or(Async::Ready(())).is_ready(),
+            None => false,
+        };
+        if fired {
+            self.rate_wakeup = None;
+        }
+    }
     fn start_closing(&mut self) {
         if !self.closing {
             self.closing = true;
@@ -214,71 +465,107 @@ impl<A, C, E, M> Lazy<A, C, E, M>
         }
     }
     fn poll_futures(&mut self) {
+        self.sweep_expired();
         loop {
             match self.futures.poll() {
                 Ok(Async::NotReady) => break,
                 Ok(Async::Ready(None)) => break,
                 Ok(Async::Ready(Some(FutureOk::Connected(task, sink)))) => {
+                    debug!("Connected to {}, probing health", task.addr());
+                    self.futures.push(Box::new(
+                        ProbeFuture::new(task, sink, &self.health)));
+                }
+                Ok(Async::Ready(Some(FutureOk::Probed(task, sink)))) => {
+                    debug!("Healthy connection to {}, detecting capabilities",
+                        task.addr());
+                    self.futures.push(Box::new(
+                        CapabilityFuture::new(task, sink, &self.capabilities)));
+                }
+                Ok(Async::Ready(Some(FutureOk::Tagged(task, sink, caps)))) => {
                     self.metrics.connection();
-                    debug!("Connected to {}", task.addr());
+                    task.set_capabilities(caps);
+                    debug!("Connection to {} advertises {:?}", task.addr(), caps);
                     // helper will add itself to the active queue on wakeup
                     self.futures.push(Box::new(SinkFuture::new(sink, task)));
                 }
                 Err(FutureErr::CantConnect(sa, err)) => {
                     self.metrics.connection_error();
                     self.errors.connection_error(sa, err);
-                    let (min, max) = self.reconnect_ms;
-                    let dur = Duration::from_millis(
-                            thread_rng().gen_range(min, max));
                     self.metrics.blacklist_add();
-                    self.blist.blacklist(sa, Instant::now() + dur);
+                    self.blist.record_failure(sa);
                     self.aligner.put(sa);
                 }
-                Err(FutureErr::Disconnected(sa, err)) => {
+                Err(FutureErr::Unhealthy(sa)) => {
+                    self.metrics.connection_error();
+                    self.errors.health_check_failed(sa);
+                    self.metrics.blacklist_add();
+                    self.blist.record_failure(sa);
+                    self.aligner.put(sa);
+                }
+                Err(FutureErr::Disconnected(sa, err, uptime)) => {
                     self.metrics.disconnect();
-                    // TODO(tailhook) blacklist connection if it was
-                    // recently connected
                     self.errors.sink_error(sa, err);
+                    if uptime >= self.stable_after {
+                        self.blist.note_success(sa);
+                    } else {
+                        // connection flapped before becoming stable;
+                        // blacklist it with a growing backoff
+                        self.metrics.blacklist_add();
+                        self.blist.record_failure(sa);
+                    }
                     self.aligner.put(sa);
                 }
                 Ok(Async::Ready(Some(FutureOk::Aborted(_)))) => {
                     self.metrics.connection_abort();
                 }
-                Ok(Async::Ready(Some(FutureOk::Closed(_)))) => {
+                Ok(Async::Ready(Some(FutureOk::Closed(sa)))) => {
                     self.metrics.disconnect();
+                    self.aligner.put(sa);
                 }
             }
         }
     }
-}
-
-impl<A, C, E, M> Sink for Lazy<A, C, E, M>
-    where A: Stream<Item=Address, Error=Void>,
-          C: Connect + 'static,
-          <C::Future as Future>::Item: Sink,
-          E: ErrorLog<
-            ConnectionError=<C::Future as Future>::Error,
-            SinkError=<<C::Future as Future>::Item as Sink>::SinkError>,
-          M: Collect + 'static,
-{
-    type SinkItem = <<C::Future as Future>::Item as Sink>::SinkItem;
-    type SinkError = private::Done;
-    fn start_send(&mut self, mut v: Self::SinkItem)
-        -> Result<AsyncSink<Self::SinkItem>, private::Done>
+    /// Send a request tagged with the capabilities a connection must
+    /// advertise before it's eligible to carry it
+    ///
+    /// This is the capability-aware counterpart of `Sink::start_send`,
+    /// which sends plain items requiring no capabilities, so any
+    /// connection may carry them. Use this instead when the pool was built with
+    /// `detect_capabilities` and some requests need to reach only a
+    /// subset of the cluster.
+    pub fn send_tagged(&mut self,
+        v: Tagged<<<C::Future as Future>::Item as Sink>::SinkItem>)
+        -> Result<AsyncSink<Tagged<<<C::Future as Future>::Item as Sink>::SinkItem>>,
+            private::Done>
     {
+        let required = v.required;
+        let mut v = v.item;
         if self.closing {
             self.poll_futures();
             if self.futures.len() == 0 {
                 return Err(private::Done);
             }
-            return Ok(AsyncSink::NotReady(v));
+            return Ok(AsyncSink::NotReady(Tagged::new(v, required)));
         } else {
             self.check_for_address_updates();
+            self.poll_rate_wakeup();
+            self.maybe_speculative_connect(required);
             'outer: loop {
+                let ready_len = self.connections.borrow().count_matching(required);
+                let mut throttled = 0;
                 loop {
-                    let ctr = self.connections.borrow_mut().next();
+                    let ctr = self.connections.borrow_mut().next_matching(required);
                     if let Some(ctr) = ctr {
                         if ctr.is_closed() { continue }
+                        if self.rate_limit.is_some() && !self.try_take_tokens(&ctr) {
+                            throttled += 1;
+                            self.connections.borrow_mut().add(ctr);
+                            if throttled >= ready_len {
+                                self.schedule_rate_wakeup();
+                                return Ok(AsyncSink::NotReady(Tagged::new(v, required)));
+                            }
+                            continue;
+                        }
                         ctr.request(v);
                         self.poll_futures();
                         if let Some(request) = ctr.request_back() {
@@ -291,7 +578,7 @@ impl<A, C, E, M> Sink for Lazy<A, C, E, M>
                         }
                     } else {
                         self.poll_futures();
-                        if !self.connections.borrow().has_ready() {
+                        if !self.connections.borrow().has_ready_matching(required) {
                             break;
                         }
                     }
@@ -299,12 +586,12 @@ impl<A, C, E, M> Sink for Lazy<A, C, E, M>
                 loop {
                     while let Some(addr) = self.do_connect() {
                         self.poll_futures();
-                        if self.connections.borrow().has_ready() {
+                        if self.connections.borrow().has_ready_matching(required) {
                             continue 'outer;
                         }
                         if !self.blist.is_failing(addr) {
                             // Waiting for connect
-                            return Ok(AsyncSink::NotReady(v));
+                            return Ok(AsyncSink::NotReady(Tagged::new(v, required)));
                         }
                     }
                     if let Async::Ready(_) = self.blist.poll() {
@@ -314,12 +601,38 @@ impl<A, C, E, M> Sink for Lazy<A, C, E, M>
                         }
                     } else {
                         // log backpressure issue, not sure how
-                        return Ok(AsyncSink::NotReady(v));
+                        return Ok(AsyncSink::NotReady(Tagged::new(v, required)));
                     }
                 }
             }
         }
     }
+}
+
+impl<A, C, E, M, H, P> Sink for Lazy<A, C, E, M, H, P>
+    where A: Stream<Item=Address, Error=Void>,
+          C: Connect + 'static,
+          <C::Future as Future>::Item: Sink,
+          E: ErrorLog<
+            ConnectionError=<C::Future as Future>::Error,
+            SinkError=<<C::Future as Future>::Item as Sink>::SinkError>,
+          M: Collect + 'static,
+          H: Health<<C::Future as Future>::Item> + 'static,
+          P: CapabilityProbe<<C::Future as Future>::Item> + 'static,
+{
+    type SinkItem = <<C::Future as Future>::Item as Sink>::SinkItem;
+    type SinkError = private::Done;
+    /// Sends a plain, untagged item, requiring no capabilities; use
+    /// `send_tagged` to route a request to connections advertising a
+    /// specific capability
+    fn start_send(&mut self, v: Self::SinkItem)
+        -> Result<AsyncSink<Self::SinkItem>, private::Done>
+    {
+        match self.send_tagged(v.into())? {
+            AsyncSink::Ready => Ok(AsyncSink::Ready),
+            AsyncSink::NotReady(tagged) => Ok(AsyncSink::NotReady(tagged.item)),
+        }
+    }
     fn poll_complete(&mut self) -> Result<Async<()>, private::Done> {
         if self.closing {
             self.poll_futures();
@@ -332,6 +645,7 @@ impl<A, C, E, M> Sink for Lazy<A, C, E, M>
             while let Async::Ready(_) = self.blist.poll() {
                 self.metrics.blacklist_remove();
             }
+            self.poll_rate_wakeup();
         }
         // TODO(tailhook) maybe we can track if connections have everything
         // flushed
@@ -347,3 +661,153 @@ impl<A, C, E, M> Sink for Lazy<A, C, E, M>
     }
 }
 
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::net::SocketAddr;
+    use std::rc::Rc;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+    use futures::{Async, AsyncSink, Future, Sink};
+    use tokio_core::reactor::{Core, Timeout};
+    use uniform::aligner::Aligner;
+    use uniform::capability::Capabilities;
+    use uniform::chan::Helper;
+    use uniform::sink::SinkFuture;
+    use super::{sweep_expired_impl, Connections, FutureOk, RateLimit};
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:1".parse().unwrap()
+    }
+
+    /// A sink that never pushes back, so the controller closing is the
+    /// only thing driving `SinkFuture` to completion
+    struct NullSink;
+    impl Sink for NullSink {
+        type SinkItem = ();
+        type SinkError = ();
+        fn start_send(&mut self, _: ()) -> Result<AsyncSink<()>, ()> {
+            Ok(AsyncSink::Ready)
+        }
+        fn poll_complete(&mut self) -> Result<Async<()>, ()> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    #[test]
+    fn sweep_expired_closes_connections_past_max_lifetime() {
+        let connections: Rc<RefCell<Connections<()>>> =
+            Rc::new(RefCell::new(Connections::new(None)));
+        let helper = Helper::new(addr(), connections.clone(), None);
+        let ctr = helper.controller();
+        connections.borrow_mut().all.insert(ctr.clone());
+
+        sleep(Duration::from_millis(20));
+        sweep_expired_impl(&connections.borrow(),
+            Some(Duration::from_millis(10)), None, Instant::now());
+        assert!(ctr.is_closed());
+    }
+
+    #[test]
+    fn sweep_expired_leaves_fresh_connections_alone() {
+        let connections: Rc<RefCell<Connections<()>>> =
+            Rc::new(RefCell::new(Connections::new(None)));
+        let helper = Helper::new(addr(), connections.clone(), None);
+        let ctr = helper.controller();
+        connections.borrow_mut().all.insert(ctr.clone());
+
+        sweep_expired_impl(&connections.borrow(),
+            Some(Duration::from_millis(200)), Some(Duration::from_millis(200)),
+            Instant::now());
+        assert!(!ctr.is_closed());
+    }
+
+    #[test]
+    fn closing_an_expired_connection_frees_its_aligner_slot() {
+        // mirrors the `FutureOk::Closed` branch in `Lazy::poll_futures`,
+        // which is what lets a connection recycled by `sweep_expired` get
+        // re-established on the next `do_connect`
+        let connections = Rc::new(RefCell::new(Connections::new(None)));
+        let helper = Helper::new(addr(), connections.clone(), None);
+        let ctr = helper.controller();
+        connections.borrow_mut().all.insert(ctr.clone());
+
+        sweep_expired_impl(&connections.borrow(),
+            Some(Duration::new(0, 0)), None, Instant::now());
+        assert!(ctr.is_closed());
+
+        let mut fut: SinkFuture<NullSink, ()> = SinkFuture::new(NullSink, helper);
+        let sa = match fut.poll() {
+            Ok(Async::Ready(FutureOk::Closed(sa))) => sa,
+            _ => panic!("expected the sink future to resolve Closed"),
+        };
+
+        let mut aligner = Aligner::new();
+        aligner.update(vec![sa], Vec::new());
+        assert_eq!(aligner.get(1, |_| false), Some(sa));
+        assert_eq!(aligner.get(1, |_| false), None);
+        aligner.put(sa);
+        assert_eq!(aligner.get(1, |_| false), Some(sa));
+    }
+
+    #[test]
+    fn empty_ready_queue_fails_the_eager_connect_gate() {
+        // `Lazy` itself can't be instantiated here (it's generic over the
+        // `Connect` trait, which lives outside `src/uniform` and isn't
+        // part of this snapshot), so `do_connect` can't be driven end to
+        // end. What's self-contained, and what `maybe_speculative_connect`
+        // actually gates on, is `has_ready_matching`: this proves an empty
+        // queue reports no match, which is exactly the condition that
+        // makes eager-connect fire a speculative `do_connect` instead of
+        // waiting for round-robin to exhaust the (empty) queue.
+        let connections: Connections<()> = Connections::new(None);
+        assert!(!connections.has_ready_matching(Capabilities::NONE));
+    }
+
+    #[test]
+    fn a_connection_that_becomes_ready_mid_flight_is_requeued_not_dropped() {
+        // covers the other half of eager-connect's contract: a `Controller`
+        // whose `ConnectFuture` resolves while a speculative connect is
+        // still in flight must land back in the ready queue rather than
+        // being silently forgotten. That hand-off happens the first time
+        // its `SinkFuture` is polled (see `SinkFuture::poll`'s `requeue`
+        // call), so it's exercised directly here without needing `Lazy`.
+        let connections: Rc<RefCell<Connections<()>>> =
+            Rc::new(RefCell::new(Connections::new(None)));
+        let helper = Helper::new(addr(), connections.clone(), None);
+        let ctr = helper.controller();
+        connections.borrow_mut().all.insert(ctr.clone());
+        assert!(!connections.borrow().has_ready());
+
+        let mut fut: SinkFuture<NullSink, ()> = SinkFuture::new(NullSink, helper);
+        match fut.poll() {
+            Ok(Async::NotReady) => {}
+            other => panic!("expected the sink future to stay open, got {:?}",
+                other.is_ok()),
+        }
+        assert!(connections.borrow().has_ready());
+    }
+
+    #[test]
+    fn pool_wakeup_fires_once_the_shared_bucket_refills() {
+        let mut core = Core::new().unwrap();
+        // mirrors the Timeout that `Lazy::schedule_rate_wakeup` arms from
+        // `Connections::pool_wakeup` whenever every ready connection is
+        // throttled
+        let mut connections: Connections<()> =
+            Connections::new(Some(RateLimit::new(1000, 1)));
+        assert!(connections.has_pool_token());
+        connections.take_pool_token();
+        assert!(!connections.has_pool_token());
+
+        let wait = connections.pool_wakeup();
+        assert!(wait > Duration::new(0, 0));
+        let timeout = Timeout::new(wait, &core.handle()).unwrap();
+        core.run(timeout).unwrap();
+
+        // the timer only fires once the bucket actually has a token again,
+        // which is what wakes the task blocked on a fully-throttled queue
+        assert!(connections.has_pool_token());
+    }
+}
+
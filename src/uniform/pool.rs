@@ -0,0 +1,50 @@
+//! The sink type returned by `LazyUniform::construct`
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use abstract_ns::Address;
+use futures::{Future, Sink};
+use futures::stream::FuturesUnordered;
+use tokio_core::reactor::{Handle, Timeout};
+
+use connect::Connect;
+use uniform::{Connections, FutureOk, FutureErr, RateLimit};
+use uniform::aligner::Aligner;
+use uniform::capability::NoCapabilities;
+use uniform::failures::Blacklist;
+use uniform::health::NoHealth;
+
+/// A sink that lazily establishes and maintains a uniform number of
+/// connections to every address yielded by the address stream
+pub struct Lazy<A, C, E, M, H=NoHealth, P=NoCapabilities>
+    where C: Connect,
+          <C::Future as Future>::Item: Sink,
+{
+    pub(crate) conn_limit: u32,
+    pub(crate) stable_after: Duration,
+    pub(crate) max_lifetime: Option<Duration>,
+    pub(crate) idle_timeout: Option<Duration>,
+    pub(crate) eager_connect: bool,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) rate_wakeup: Option<Timeout>,
+    pub(crate) health: H,
+    pub(crate) capabilities: P,
+    pub(crate) handle: Handle,
+    pub(crate) futures: FuturesUnordered<Box<Future<
+        Item=FutureOk<<C::Future as Future>::Item>,
+        Error=FutureErr<
+            <C::Future as Future>::Error,
+            <<C::Future as Future>::Item as Sink>::SinkError>,
+    >>>,
+    pub(crate) connections:
+        Rc<RefCell<Connections<<<C::Future as Future>::Item as Sink>::SinkItem>>>,
+    pub(crate) blist: Blacklist,
+    pub(crate) aligner: Aligner,
+    pub(crate) closing: bool,
+    pub(crate) cur_address: Address,
+    pub(crate) address: A,
+    pub(crate) connector: C,
+    pub(crate) errors: E,
+    pub(crate) metrics: M,
+}
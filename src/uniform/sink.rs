@@ -0,0 +1,52 @@
+//! Drives an established connection, ferrying queued items into the sink
+//! and re-registering the controller for round-robin once it's ready again
+use std::marker::PhantomData;
+use std::time::Instant;
+
+use futures::{Future, Async, AsyncSink, Sink};
+
+use uniform::{FutureOk, FutureErr};
+use uniform::chan::Helper;
+
+/// Owns a connected sink and the `Helper` used to hand it work
+pub(crate) struct SinkFuture<S: Sink, E> {
+    sink: S,
+    task: Option<Helper<<S as Sink>::SinkItem>>,
+    _connect_error: PhantomData<E>,
+}
+
+impl<S: Sink, E> SinkFuture<S, E> {
+    pub(crate) fn new(sink: S, task: Helper<S::SinkItem>) -> SinkFuture<S, E> {
+        SinkFuture { sink, task: Some(task), _connect_error: PhantomData }
+    }
+}
+
+impl<S: Sink, E> Future for SinkFuture<S, E> {
+    type Item = FutureOk<S>;
+    type Error = FutureErr<E, S::SinkError>;
+    fn poll(&mut self) -> Result<Async<Self::Item>, Self::Error> {
+        let task = self.task.take().expect("poll after done");
+        loop {
+            if let Some(item) = task.take_pending() {
+                match self.sink.start_send(item) {
+                    Ok(AsyncSink::Ready) => continue,
+                    Ok(AsyncSink::NotReady(item)) => task.put_back(item),
+                    Err(e) => {
+                        let uptime = Instant::now() - task.connected_at();
+                        return Err(FutureErr::Disconnected(task.addr(), e, uptime));
+                    }
+                }
+            }
+            if let Err(e) = self.sink.poll_complete() {
+                let uptime = Instant::now() - task.connected_at();
+                return Err(FutureErr::Disconnected(task.addr(), e, uptime));
+            }
+            if task.is_closed() {
+                return Ok(Async::Ready(FutureOk::Closed(task.addr())));
+            }
+            task.requeue();
+            self.task = Some(task);
+            return Ok(Async::NotReady);
+        }
+    }
+}